@@ -31,6 +31,8 @@ mod path;
 mod poll;
 mod sandboxed_tty_writer;
 pub mod snapshots;
+#[cfg(unix)]
+pub mod sockets;
 mod sys;
 mod virtfs;
 pub mod wasi;
@@ -0,0 +1,539 @@
+use super::rate::TokenBucket;
+use super::{AddressFamily, AddressTransform, Socks5Proxy, SystemTcpSocket};
+use std::fmt;
+use std::io::{Error, Result};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// An embedder-provided hook that takes over raw socket creation; see
+/// [`NetworkContext::set_socket_factory`].
+///
+/// The factory is handed the requested address family and returns the
+/// socket to use — typically `SystemTcpSocket::new` plus whatever
+/// mandatory options the embedding insists on, but it may equally wrap
+/// a descriptor obtained elsewhere. Context-level concerns (counting,
+/// lifetime limits, transforms, proxying) are layered on by the context
+/// after the factory returns, exactly as for factory-less creation.
+#[derive(Clone)]
+pub struct SocketFactory(Arc<dyn Fn(AddressFamily) -> Result<SystemTcpSocket> + Send + Sync>);
+
+impl SocketFactory {
+    pub fn new<F>(factory: F) -> Self
+    where
+        F: Fn(AddressFamily) -> Result<SystemTcpSocket> + Send + Sync + 'static,
+    {
+        Self(Arc::new(factory))
+    }
+
+    /// Runs the factory for one socket.
+    pub fn create(&self, family: AddressFamily) -> Result<SystemTcpSocket> {
+        (self.0)(family)
+    }
+}
+
+impl fmt::Debug for SocketFactory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SocketFactory(..)")
+    }
+}
+
+/// Shared bookkeeping for all sockets belonging to one embedding (one
+/// store, once this is wired up to WASI).
+///
+/// Sockets created through the context are counted while they are open;
+/// sockets accepted from a counted listener are counted too. When the
+/// context is dropped with sockets still open — typically because a
+/// guest leaked connections across store teardown — a warning is logged,
+/// giving embedders visibility they otherwise lack. The check is cheap
+/// but can be disabled entirely with [`set_warn_on_leak`]; it defaults
+/// to on only in debug builds.
+///
+/// [`set_warn_on_leak`]: Self::set_warn_on_leak
+#[derive(Debug)]
+pub struct NetworkContext {
+    open_sockets: Arc<AtomicUsize>,
+    warn_on_leak: bool,
+    max_connection_lifetime: Option<Duration>,
+    byte_budgets: (Option<u64>, Option<u64>),
+    listening_sockets: Arc<AtomicUsize>,
+    max_listeners: Option<usize>,
+    address_transform: Option<AddressTransform>,
+    socks5_proxy: Option<Socks5Proxy>,
+    socket_factory: Option<SocketFactory>,
+    egress_limit: Option<Arc<Mutex<TokenBucket>>>,
+}
+
+impl NetworkContext {
+    pub fn new() -> Self {
+        Self {
+            open_sockets: Arc::new(AtomicUsize::new(0)),
+            warn_on_leak: cfg!(debug_assertions),
+            max_connection_lifetime: None,
+            byte_budgets: (None, None),
+            listening_sockets: Arc::new(AtomicUsize::new(0)),
+            max_listeners: None,
+            address_transform: None,
+            socks5_proxy: None,
+            socket_factory: None,
+            egress_limit: None,
+        }
+    }
+
+    /// Enables or disables the leaked-socket warning on drop.
+    pub fn set_warn_on_leak(&mut self, enabled: bool) {
+        self.warn_on_leak = enabled;
+    }
+
+    /// Caps the lifetime of connections made by sockets created after
+    /// this call. Once a connection outlives the limit, its reads and
+    /// writes fail with `ETIMEDOUT` and it is shut down, forcing the
+    /// guest to reconnect — useful where credential rotation requires
+    /// periodic re-establishment. Disabled by default.
+    pub fn set_max_connection_lifetime(&mut self, limit: Option<Duration>) {
+        self.max_connection_lifetime = limit;
+    }
+
+    /// Caps the cumulative bytes a connection may read and/or write over
+    /// its lifetime, for metered or billed environments. Once a counter
+    /// reaches its budget the connection fails with `EDQUOT` and is shut
+    /// down. Disabled by default.
+    pub fn set_byte_budgets(&mut self, read: Option<u64>, write: Option<u64>) {
+        self.byte_budgets = (read, write);
+    }
+
+    /// Caps how many sockets created through this context may sit in
+    /// the `Listening` state at once; `listen` fails with `EMFILE` once
+    /// the cap is reached. A slot is released when a listener is
+    /// cancelled or dropped. Unlimited by default.
+    pub fn set_max_listeners(&mut self, limit: Option<usize>) {
+        self.max_listeners = limit;
+    }
+
+    /// Returns how many sockets created through this context are
+    /// currently listening.
+    pub fn listening_socket_count(&self) -> usize {
+        self.listening_sockets.load(Ordering::SeqCst)
+    }
+
+    /// Installs (or removes) a hook that may rewrite or reject every
+    /// bind and connect target of sockets created afterwards; see
+    /// [`AddressTransform`]. `None` by default.
+    pub fn set_address_transform(&mut self, transform: Option<AddressTransform>) {
+        self.address_transform = transform;
+    }
+
+    /// Routes all connects made by sockets created afterwards through
+    /// the given SOCKS5 proxy, or directly again with `None`; see
+    /// [`Socks5Proxy`]. The tunnel is transparent to the caller. `None`
+    /// by default.
+    pub fn set_socks5_proxy(&mut self, proxy: Option<Socks5Proxy>) {
+        self.socks5_proxy = proxy;
+    }
+
+    /// Caps the combined outbound bandwidth of all sockets created
+    /// through this context after the call, in bytes per second.
+    ///
+    /// Where [`SystemTcpWriter::set_rate_limit`] paces one connection,
+    /// this paces the store: every writer draws from one shared token
+    /// bucket, so a guest cannot multiply its bandwidth by opening more
+    /// connections. Writes beyond the aggregate rate fail with
+    /// `WouldBlock` and `poll_write` withholds readiness until tokens
+    /// accrue. A zero rate is refused with `EINVAL`. `None` — the
+    /// default — removes the cap for sockets created afterwards;
+    /// existing sockets keep the bucket they were created with.
+    ///
+    /// [`SystemTcpWriter::set_rate_limit`]: super::SystemTcpWriter::set_rate_limit
+    pub fn set_egress_limit(&mut self, bytes_per_second: Option<u64>) -> Result<()> {
+        if bytes_per_second == Some(0) {
+            return Err(Error::from_raw_os_error(libc::EINVAL));
+        }
+        self.egress_limit =
+            bytes_per_second.map(|rate| Arc::new(Mutex::new(TokenBucket::new(rate))));
+        Ok(())
+    }
+
+    /// Installs (or removes) a factory that takes over the raw creation
+    /// step of every socket made through this context; see
+    /// [`SocketFactory`]. `None` — the default — means plain
+    /// `SystemTcpSocket::new`.
+    pub fn set_socket_factory(&mut self, factory: Option<SocketFactory>) {
+        self.socket_factory = factory;
+    }
+
+    /// Creates a TCP socket counted against this context.
+    pub fn new_tcp_socket(&self, family: AddressFamily) -> Result<SystemTcpSocket> {
+        let mut socket = match &self.socket_factory {
+            Some(factory) => factory.create(family)?,
+            None => SystemTcpSocket::new(family)?,
+        };
+        socket.attach_open_count(Arc::clone(&self.open_sockets));
+        socket.set_max_lifetime(self.max_connection_lifetime);
+        socket.set_byte_budgets(self.byte_budgets.0, self.byte_budgets.1);
+        if let Some(max) = self.max_listeners {
+            socket.set_listen_limit(Some((Arc::clone(&self.listening_sockets), max)));
+        }
+        socket.set_address_transform(self.address_transform.clone());
+        socket.set_socks5_proxy(self.socks5_proxy.clone());
+        socket.set_shared_egress(self.egress_limit.clone());
+        Ok(socket)
+    }
+
+    /// Creates `count` TCP sockets of the same family in one call,
+    /// saving per-socket host-call overhead for embedders that open
+    /// connection pools up front. Creation is all-or-nothing: if any
+    /// socket fails, those already created are closed and the error is
+    /// returned.
+    pub fn new_tcp_sockets(
+        &self,
+        family: AddressFamily,
+        count: usize,
+    ) -> Result<Vec<SystemTcpSocket>> {
+        let mut sockets = Vec::with_capacity(count);
+        for _ in 0..count {
+            // An early return drops (and closes) the sockets made so far.
+            sockets.push(self.new_tcp_socket(family)?);
+        }
+        Ok(sockets)
+    }
+
+    /// Returns how many sockets created through this context are still
+    /// open.
+    pub fn open_socket_count(&self) -> usize {
+        self.open_sockets.load(Ordering::SeqCst)
+    }
+
+    /// Returns the number of sockets that would be reported as leaked if
+    /// the context were dropped right now, or `None` if there is nothing
+    /// to report (or reporting is disabled).
+    pub fn leak_report(&self) -> Option<usize> {
+        let count = self.open_socket_count();
+        if self.warn_on_leak && count > 0 {
+            Some(count)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for NetworkContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for NetworkContext {
+    fn drop(&mut self) {
+        if let Some(count) = self.leak_report() {
+            log::warn!(
+                "network context dropped with {} socket(s) still open",
+                count
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leaked_sockets_are_reported_on_drop() {
+        let mut context = NetworkContext::new();
+        context.set_warn_on_leak(true);
+
+        let a = context.new_tcp_socket(AddressFamily::Inet4).unwrap();
+        let b = context.new_tcp_socket(AddressFamily::Inet4).unwrap();
+        assert_eq!(context.open_socket_count(), 2);
+        assert_eq!(context.leak_report(), Some(2));
+
+        drop(a);
+        assert_eq!(context.leak_report(), Some(1));
+
+        drop(b);
+        assert_eq!(context.open_socket_count(), 0);
+        assert_eq!(context.leak_report(), None);
+    }
+
+    #[test]
+    fn batch_creation_is_counted() {
+        let mut context = NetworkContext::new();
+        context.set_warn_on_leak(false);
+        let sockets = context.new_tcp_sockets(AddressFamily::Inet6, 4).unwrap();
+        assert_eq!(sockets.len(), 4);
+        assert_eq!(context.open_socket_count(), 4);
+        for socket in &sockets {
+            assert_eq!(socket.address_family(), AddressFamily::Inet6);
+        }
+        drop(sockets);
+        assert_eq!(context.open_socket_count(), 0);
+    }
+
+    #[test]
+    fn address_transform_redirects_connects() {
+        use std::io::Error;
+        use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+        let loopback = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
+
+        let mut listener_context = NetworkContext::new();
+        listener_context.set_warn_on_leak(false);
+        let mut listener = listener_context
+            .new_tcp_socket(AddressFamily::Inet4)
+            .unwrap();
+        listener.bind(loopback).unwrap();
+        listener.listen(4).unwrap();
+        let real = listener.local_addr().unwrap();
+
+        // Every connect, no matter where the guest aimed it, lands on
+        // the listener; port 9 is refused outright.
+        let mut context = NetworkContext::new();
+        context.set_warn_on_leak(false);
+        context.set_address_transform(Some(AddressTransform::new(move |requested| {
+            if requested.port() == 9 {
+                return Err(Error::from_raw_os_error(libc::EACCES));
+            }
+            Ok(real)
+        })));
+
+        let mut client = context.new_tcp_socket(AddressFamily::Inet4).unwrap();
+        let decoy = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), 80);
+        client.connect_non_boxing(decoy).unwrap();
+        assert_eq!(client.remote_addr().unwrap(), real);
+
+        let mut refused = context.new_tcp_socket(AddressFamily::Inet4).unwrap();
+        let rejected = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 9);
+        assert_eq!(
+            refused.start_connect(rejected).unwrap_err().raw_os_error(),
+            Some(libc::EACCES)
+        );
+    }
+
+    /// A single-connection SOCKS5 server: performs the handshake
+    /// (optionally demanding username/password auth) and then echoes the
+    /// tunnel bytes back, so a test can verify the data path survived
+    /// the negotiation.
+    fn spawn_mock_socks5(
+        expect_auth: Option<(&'static str, &'static str)>,
+    ) -> (std::net::SocketAddr, std::thread::JoinHandle<Vec<u8>>) {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+
+            let mut greeting = [0u8; 2];
+            stream.read_exact(&mut greeting).unwrap();
+            assert_eq!(greeting[0], 0x05);
+            let mut methods = vec![0u8; greeting[1] as usize];
+            stream.read_exact(&mut methods).unwrap();
+
+            match expect_auth {
+                None => {
+                    assert!(methods.contains(&0x00));
+                    stream.write_all(&[0x05, 0x00]).unwrap();
+                }
+                Some((user, pass)) => {
+                    assert!(methods.contains(&0x02));
+                    stream.write_all(&[0x05, 0x02]).unwrap();
+                    let mut header = [0u8; 2];
+                    stream.read_exact(&mut header).unwrap();
+                    assert_eq!(header[0], 0x01);
+                    let mut username = vec![0u8; header[1] as usize];
+                    stream.read_exact(&mut username).unwrap();
+                    let mut len = [0u8; 1];
+                    stream.read_exact(&mut len).unwrap();
+                    let mut password = vec![0u8; len[0] as usize];
+                    stream.read_exact(&mut password).unwrap();
+                    let ok = username == user.as_bytes() && password == pass.as_bytes();
+                    stream
+                        .write_all(&[0x01, if ok { 0x00 } else { 0x01 }])
+                        .unwrap();
+                    assert!(ok, "mock proxy got wrong credentials");
+                }
+            }
+
+            let mut request = [0u8; 4];
+            stream.read_exact(&mut request).unwrap();
+            assert_eq!(&request[..3], &[0x05, 0x01, 0x00]);
+            assert_eq!(request[3], 0x01, "expected an IPv4 target");
+            let mut target = [0u8; 6];
+            stream.read_exact(&mut target).unwrap();
+            stream
+                .write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+                .unwrap();
+
+            // Echo the tunneled payload back.
+            let mut payload = [0u8; 4];
+            stream.read_exact(&mut payload).unwrap();
+            stream.write_all(&payload).unwrap();
+            target.to_vec()
+        });
+        (addr, handle)
+    }
+
+    #[test]
+    fn connects_tunnel_through_a_socks5_proxy() {
+        use std::io::{Read, Write};
+        use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+        for auth in &[None, Some(("user", "hunter2"))] {
+            let (proxy_addr, proxy) = spawn_mock_socks5(*auth);
+
+            let mut context = NetworkContext::new();
+            context.set_warn_on_leak(false);
+            context.set_socks5_proxy(Some(match auth {
+                None => Socks5Proxy::new(proxy_addr),
+                Some((user, pass)) => Socks5Proxy::with_auth(proxy_addr, user, pass),
+            }));
+
+            // The target never has to exist: only the proxy sees it.
+            let target = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 7)), 4242);
+            let mut client = context.new_tcp_socket(AddressFamily::Inet4).unwrap();
+            client.connect_non_boxing(target).unwrap();
+
+            let (mut reader, mut writer) = client.split().unwrap();
+            writer.write_all(b"ping").unwrap();
+            let mut reply = [0u8; 4];
+            let mut filled = 0;
+            while filled < reply.len() {
+                match reader.read(&mut reply[filled..]) {
+                    Ok(0) => panic!("tunnel closed early"),
+                    Ok(n) => filled += n,
+                    Err(ref err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                        std::thread::sleep(std::time::Duration::from_millis(1));
+                    }
+                    Err(err) => panic!("tunnel read failed: {}", err),
+                }
+            }
+            assert_eq!(&reply, b"ping");
+
+            // The proxy was asked for the address the guest dialed.
+            let seen = proxy.join().unwrap();
+            assert_eq!(&seen[..4], &[192, 0, 2, 7]);
+            assert_eq!(u16::from_be_bytes([seen[4], seen[5]]), 4242);
+        }
+    }
+
+    #[test]
+    fn socket_factory_applies_mandatory_options() {
+        let mut context = NetworkContext::new();
+        context.set_warn_on_leak(false);
+        // An embedding that insists every socket runs with Nagle off.
+        context.set_socket_factory(Some(SocketFactory::new(|family| {
+            let mut socket = SystemTcpSocket::new(family)?;
+            socket.set_no_delay(true)?;
+            Ok(socket)
+        })));
+
+        let socket = context.new_tcp_socket(AddressFamily::Inet4).unwrap();
+        assert!(socket.no_delay().unwrap());
+        // Context-level bookkeeping still applies around the factory.
+        assert_eq!(context.open_socket_count(), 1);
+
+        context.set_socket_factory(None);
+        let plain = context.new_tcp_socket(AddressFamily::Inet4).unwrap();
+        assert!(!plain.no_delay().unwrap());
+    }
+
+    #[test]
+    fn egress_limit_caps_combined_throughput() {
+        use std::io::Write;
+        use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+        use std::time::{Duration, Instant};
+        let loopback = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
+
+        const RATE: u64 = 10_000; // bytes per second
+
+        let mut context = NetworkContext::new();
+        context.set_warn_on_leak(false);
+        // The context-wide setter refuses a zero rate like the
+        // per-socket ones.
+        assert_eq!(
+            context.set_egress_limit(Some(0)).unwrap_err().raw_os_error(),
+            Some(libc::EINVAL)
+        );
+        context.set_egress_limit(Some(RATE)).unwrap();
+
+        // Two independent connections; their writers share one bucket.
+        let mut listener = SystemTcpSocket::new(AddressFamily::Inet4).unwrap();
+        listener.bind(loopback).unwrap();
+        listener.listen(4).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut writers = Vec::new();
+        for _ in 0..2 {
+            let mut client = context.new_tcp_socket(AddressFamily::Inet4).unwrap();
+            client.connect_non_boxing(addr).unwrap();
+            let (_, writer) = client.split().unwrap();
+            writers.push((client, writer));
+        }
+
+        let chunk = [0u8; 1024];
+        let window = Duration::from_millis(400);
+        let start = Instant::now();
+        let mut sent = 0u64;
+        while start.elapsed() < window {
+            for (_, writer) in &mut writers {
+                match writer.write(&chunk) {
+                    Ok(written) => sent += written as u64,
+                    Err(ref err) if err.kind() == std::io::ErrorKind::WouldBlock => {}
+                    Err(err) => panic!("write failed: {}", err),
+                }
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        // The bucket starts with one second's worth of burst; beyond
+        // that, the combined rate converges on the configured one. The
+        // bounds are generous to keep scheduler jitter from flaking.
+        let elapsed = start.elapsed().as_millis() as u64;
+        let ceiling = RATE + RATE * elapsed / 1000 + RATE / 4;
+        assert!(
+            sent <= ceiling,
+            "sent {} bytes, expected at most {}",
+            sent,
+            ceiling
+        );
+        assert!(sent >= RATE, "sent {} bytes, the initial burst alone is {}", sent, RATE);
+    }
+
+    #[test]
+    fn listener_cap_rejects_the_excess() {
+        use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+        let loopback = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
+
+        let mut context = NetworkContext::new();
+        context.set_warn_on_leak(false);
+        context.set_max_listeners(Some(2));
+
+        let mut sockets = context.new_tcp_sockets(AddressFamily::Inet4, 3).unwrap();
+        for socket in &mut sockets {
+            socket.bind(loopback).unwrap();
+        }
+        sockets[0].listen(4).unwrap();
+        sockets[1].listen(4).unwrap();
+        assert_eq!(context.listening_socket_count(), 2);
+        assert_eq!(
+            sockets[2].listen(4).unwrap_err().raw_os_error(),
+            Some(libc::EMFILE)
+        );
+
+        // Retiring a listener frees its slot for the rejected one.
+        sockets[0].cancel_accept().unwrap();
+        assert_eq!(context.listening_socket_count(), 1);
+        sockets[2].listen(4).unwrap();
+        assert_eq!(context.listening_socket_count(), 2);
+
+        drop(sockets);
+        assert_eq!(context.listening_socket_count(), 0);
+    }
+
+    #[test]
+    fn leak_reporting_can_be_disabled() {
+        let mut context = NetworkContext::new();
+        context.set_warn_on_leak(false);
+        let _socket = context.new_tcp_socket(AddressFamily::Inet4).unwrap();
+        assert_eq!(context.leak_report(), None);
+    }
+}
@@ -0,0 +1,196 @@
+use std::net::{IpAddr, Ipv4Addr};
+
+/// An allowlist of IP networks in CIDR form.
+///
+/// Used by the listener accept path to decide which source addresses may
+/// reach the guest at all; see `SystemTcpSocket::set_accept_filter`. An
+/// empty matcher matches nothing.
+#[derive(Debug, Clone, Default)]
+pub struct IpNetMatcher {
+    rules: Vec<(IpAddr, u8)>,
+}
+
+impl IpNetMatcher {
+    pub fn new() -> Self {
+        Self { rules: Vec::new() }
+    }
+
+    /// Adds a network to the allowlist. A `prefix_len` longer than the
+    /// address is clamped to the address width.
+    pub fn allow(&mut self, network: IpAddr, prefix_len: u8) -> &mut Self {
+        self.rules.push((network, prefix_len));
+        self
+    }
+
+    /// Returns whether `addr` falls inside any allowed network.
+    pub fn matches(&self, addr: IpAddr) -> bool {
+        self.rules
+            .iter()
+            .any(|&(network, prefix_len)| match (network, addr) {
+                (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                    prefix_matches(&network.octets(), &addr.octets(), prefix_len)
+                }
+                (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                    prefix_matches(&network.octets(), &addr.octets(), prefix_len)
+                }
+                _ => false,
+            })
+    }
+}
+
+/// The locality class of an IP address, from most to least local.
+///
+/// This is the vocabulary for guest-visible allow/deny policy that keys
+/// on "how far away is this peer" rather than on explicit CIDR rules;
+/// for the latter see [`IpNetMatcher`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressScope {
+    /// The wildcard address — not a destination at all.
+    Unspecified,
+    /// The host itself (`127.0.0.0/8`, `::1`).
+    Loopback,
+    /// Valid only on the local link (`169.254.0.0/16`, `fe80::/10`).
+    LinkLocal,
+    /// Routable within a site but not globally (RFC 1918 ranges,
+    /// `fc00::/7` unique-local).
+    Private,
+    /// A group address (`224.0.0.0/4`, `ff00::/8`).
+    Multicast,
+    /// Everything else — presumed globally routable.
+    Public,
+}
+
+/// Classifies `addr` into an [`AddressScope`].
+///
+/// IPv4-mapped IPv6 addresses (`::ffff:a.b.c.d`) are classified as the
+/// embedded IPv4 address, so a dual-stack listener sees the same scope
+/// for a peer regardless of which family the kernel reported it in.
+pub fn address_scope(addr: &IpAddr) -> AddressScope {
+    match addr {
+        IpAddr::V4(addr) => ipv4_scope(addr),
+        IpAddr::V6(addr) => {
+            let octets = addr.octets();
+            if octets[..10] == [0; 10] && octets[10..12] == [0xff, 0xff] {
+                return ipv4_scope(&Ipv4Addr::new(
+                    octets[12], octets[13], octets[14], octets[15],
+                ));
+            }
+            if addr.is_unspecified() {
+                AddressScope::Unspecified
+            } else if addr.is_loopback() {
+                AddressScope::Loopback
+            } else if octets[0] == 0xfe && octets[1] & 0xc0 == 0x80 {
+                AddressScope::LinkLocal
+            } else if octets[0] & 0xfe == 0xfc {
+                AddressScope::Private
+            } else if octets[0] == 0xff {
+                AddressScope::Multicast
+            } else {
+                AddressScope::Public
+            }
+        }
+    }
+}
+
+fn ipv4_scope(addr: &Ipv4Addr) -> AddressScope {
+    if addr.is_unspecified() {
+        AddressScope::Unspecified
+    } else if addr.is_loopback() {
+        AddressScope::Loopback
+    } else if addr.is_link_local() {
+        AddressScope::LinkLocal
+    } else if addr.is_private() {
+        AddressScope::Private
+    } else if addr.is_multicast() {
+        AddressScope::Multicast
+    } else {
+        AddressScope::Public
+    }
+}
+
+/// Compares the leading `prefix_len` bits of two addresses.
+fn prefix_matches(network: &[u8], addr: &[u8], prefix_len: u8) -> bool {
+    let prefix_len = (prefix_len as usize).min(network.len() * 8);
+    let full_bytes = prefix_len / 8;
+    if network[..full_bytes] != addr[..full_bytes] {
+        return false;
+    }
+    let trailing_bits = prefix_len % 8;
+    if trailing_bits == 0 {
+        return true;
+    }
+    let mask = 0xffu8 << (8 - trailing_bits);
+    (network[full_bytes] & mask) == (addr[full_bytes] & mask)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn cidr_matching() {
+        let mut matcher = IpNetMatcher::new();
+        matcher.allow(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0)), 8);
+        matcher.allow(IpAddr::V4(Ipv4Addr::new(192, 168, 4, 0)), 22);
+        matcher.allow(IpAddr::V6(Ipv6Addr::LOCALHOST), 128);
+
+        assert!(matcher.matches(IpAddr::V4(Ipv4Addr::new(10, 255, 1, 2))));
+        assert!(!matcher.matches(IpAddr::V4(Ipv4Addr::new(11, 0, 0, 1))));
+        assert!(matcher.matches(IpAddr::V4(Ipv4Addr::new(192, 168, 7, 9))));
+        assert!(!matcher.matches(IpAddr::V4(Ipv4Addr::new(192, 168, 8, 1))));
+        assert!(matcher.matches(IpAddr::V6(Ipv6Addr::LOCALHOST)));
+        assert!(!matcher.matches(IpAddr::V6(Ipv6Addr::UNSPECIFIED)));
+    }
+
+    #[test]
+    fn empty_matcher_matches_nothing() {
+        let matcher = IpNetMatcher::new();
+        assert!(!matcher.matches(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+    }
+
+    #[test]
+    fn zero_prefix_matches_the_whole_family() {
+        let mut matcher = IpNetMatcher::new();
+        matcher.allow(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0);
+        assert!(matcher.matches(IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7))));
+        assert!(!matcher.matches(IpAddr::V6(Ipv6Addr::LOCALHOST)));
+    }
+
+    #[test]
+    fn representative_addresses_classify_by_scope() {
+        fn scope(addr: &str) -> AddressScope {
+            address_scope(&addr.parse().unwrap())
+        }
+        assert_eq!(scope("0.0.0.0"), AddressScope::Unspecified);
+        assert_eq!(scope("::"), AddressScope::Unspecified);
+        assert_eq!(scope("127.0.0.1"), AddressScope::Loopback);
+        assert_eq!(scope("::1"), AddressScope::Loopback);
+        assert_eq!(scope("169.254.13.37"), AddressScope::LinkLocal);
+        assert_eq!(scope("fe80::1"), AddressScope::LinkLocal);
+        assert_eq!(scope("10.0.0.1"), AddressScope::Private);
+        assert_eq!(scope("172.16.0.1"), AddressScope::Private);
+        assert_eq!(scope("192.168.1.1"), AddressScope::Private);
+        assert_eq!(scope("fd12:3456::1"), AddressScope::Private);
+        assert_eq!(scope("224.0.0.251"), AddressScope::Multicast);
+        assert_eq!(scope("ff02::1"), AddressScope::Multicast);
+        assert_eq!(scope("93.184.216.34"), AddressScope::Public);
+        assert_eq!(scope("2001:db8::1"), AddressScope::Public);
+    }
+
+    #[test]
+    fn mapped_addresses_take_the_embedded_v4_scope() {
+        assert_eq!(
+            address_scope(&"::ffff:127.0.0.1".parse().unwrap()),
+            AddressScope::Loopback
+        );
+        assert_eq!(
+            address_scope(&"::ffff:192.168.0.2".parse().unwrap()),
+            AddressScope::Private
+        );
+        assert_eq!(
+            address_scope(&"::ffff:8.8.8.8".parse().unwrap()),
+            AddressScope::Public
+        );
+    }
+}
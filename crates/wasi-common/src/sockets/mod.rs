@@ -0,0 +1,146 @@
+//! Experimental host-side networking support.
+//!
+//! WASI itself does not yet expose a sockets API; the types in this module
+//! are the host-side building blocks we expect a future `wasi-sockets`
+//! implementation to sit on top of. Until that wiring exists they are
+//! usable directly by embedders and are exercised by unit tests only.
+//!
+//! Everything here is Unix-only for now, mirroring the split between
+//! `yanix` and `winx` elsewhere in this crate.
+
+mod context;
+mod filter;
+mod rate;
+pub mod resolve;
+mod socks;
+mod tcp;
+pub mod udp;
+
+pub use context::{NetworkContext, SocketFactory};
+pub use filter::{address_scope, AddressScope, IpNetMatcher};
+pub use socks::Socks5Proxy;
+pub use tcp::{
+    AddressFamily, AddressTransform, CongestionWindow, ConnectionOrigin, ErrorStatistics,
+    IpOptions, ListenerShutdown, SocketOptions, SystemTcpReader,
+    SystemTcpSocket, SystemTcpWriter, TcpFsmState, TcpInfo, TcpMetrics, TcpRepairState, TcpState,
+    TcpStatistics,
+};
+
+use std::io::{Error, Result};
+
+/// Converts a `-1`-on-failure libc return value into an `io::Result`,
+/// in the style of `std::sys::cvt`.
+fn cvt(rc: libc::c_int) -> Result<libc::c_int> {
+    if rc == -1 {
+        Err(Error::last_os_error())
+    } else {
+        Ok(rc)
+    }
+}
+
+/// How many times an `EINTR`-interrupted syscall is reissued before the
+/// error is surfaced after all. `getsockopt`/`setsockopt` do not block,
+/// so one retry should always suffice; the bound only guards against a
+/// pathological signal storm turning the loop infinite.
+const EINTR_RETRIES: u32 = 16;
+
+/// Like [`cvt`], but reissues the call while it fails with `EINTR`.
+///
+/// A signal landing mid-`getsockopt` is not an answer the caller can
+/// act on — every option getter and setter goes through this so the
+/// interruption never reaches the guest as an error.
+fn cvt_r<F: FnMut() -> libc::c_int>(mut call: F) -> Result<libc::c_int> {
+    let mut attempts = 0;
+    loop {
+        match cvt(call()) {
+            Err(ref err)
+                if err.raw_os_error() == Some(libc::EINTR) && attempts < EINTR_RETRIES =>
+            {
+                attempts += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Reads an integer-valued socket option.
+fn getsockopt_int(
+    fd: std::os::unix::io::RawFd,
+    level: libc::c_int,
+    option: libc::c_int,
+) -> Result<libc::c_int> {
+    let mut value: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    cvt_r(|| unsafe {
+        libc::getsockopt(
+            fd,
+            level,
+            option,
+            &mut value as *mut _ as *mut libc::c_void,
+            &mut len,
+        )
+    })?;
+    Ok(value)
+}
+
+/// Writes an integer-valued socket option.
+fn setsockopt_int(
+    fd: std::os::unix::io::RawFd,
+    level: libc::c_int,
+    option: libc::c_int,
+    value: libc::c_int,
+) -> Result<()> {
+    cvt_r(|| unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            option,
+            &value as *const _ as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn interrupted_calls_are_retried_within_the_bound() {
+        // Stand-ins for an interruptible syscall: fail with EINTR a set
+        // number of times, then succeed.
+        let mut interruptions = 3;
+        let result = cvt_r(|| {
+            if interruptions > 0 {
+                interruptions -= 1;
+                unsafe { *libc::__errno_location() = libc::EINTR };
+                -1
+            } else {
+                7
+            }
+        });
+        assert_eq!(result.unwrap(), 7);
+
+        // A signal storm that never lets the call through eventually
+        // surfaces the EINTR instead of spinning forever.
+        let err = cvt_r(|| {
+            unsafe { *libc::__errno_location() = libc::EINTR };
+            -1
+        })
+        .unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EINTR));
+
+        // Other errors are not retried.
+        let mut calls = 0;
+        let err = cvt_r(|| {
+            calls += 1;
+            unsafe { *libc::__errno_location() = libc::EBADF };
+            -1
+        })
+        .unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EBADF));
+        assert_eq!(calls, 1);
+    }
+}
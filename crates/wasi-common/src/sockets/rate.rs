@@ -0,0 +1,118 @@
+use std::time::{Duration, Instant};
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// An integer token bucket used to pace socket I/O, where one token is
+/// one byte.
+///
+/// The bucket holds at most one second's worth of tokens, which bounds
+/// the burst a connection can emit after sitting idle. All arithmetic is
+/// integral; sub-token remainders are carried forward rather than
+/// dropped, so the long-run rate converges on the configured one.
+#[derive(Debug)]
+pub struct TokenBucket {
+    bytes_per_second: u64,
+    available: u64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Creates a bucket that refills at `bytes_per_second`, starting full.
+    pub fn new(bytes_per_second: u64) -> Self {
+        assert!(bytes_per_second > 0, "rate limit must be non-zero");
+        Self {
+            bytes_per_second,
+            available: bytes_per_second,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Credits tokens earned between the last refill and `now`.
+    pub fn refill(&mut self, now: Instant) {
+        let elapsed = match now.checked_duration_since(self.last_refill) {
+            Some(elapsed) => elapsed,
+            None => return,
+        };
+        let earned = elapsed.as_nanos() * u128::from(self.bytes_per_second) / NANOS_PER_SEC;
+        if earned == 0 {
+            return;
+        }
+        // Advance the refill time only by the span the earned tokens
+        // represent so that fractional-token remainders carry over.
+        let spent_nanos = earned * NANOS_PER_SEC / u128::from(self.bytes_per_second);
+        self.last_refill += Duration::from_nanos(spent_nanos as u64);
+        self.available = self
+            .available
+            .saturating_add(earned as u64)
+            .min(self.bytes_per_second);
+    }
+
+    /// Reports how many tokens are currently available, without taking
+    /// any; callers should [`refill`](Self::refill) first.
+    pub fn available(&self) -> u64 {
+        self.available
+    }
+
+    /// Takes up to `want` tokens, returning how many were granted.
+    pub fn take(&mut self, want: usize) -> usize {
+        let granted = self.available.min(want as u64);
+        self.available -= granted;
+        granted as usize
+    }
+
+    /// Returns tokens that were taken but not used (e.g. a short write).
+    pub fn give_back(&mut self, unused: usize) {
+        self.available = self
+            .available
+            .saturating_add(unused as u64)
+            .min(self.bytes_per_second);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grants_at_the_configured_rate() {
+        let mut bucket = TokenBucket::new(1000);
+        let start = Instant::now();
+        bucket.last_refill = start;
+        bucket.available = 0;
+
+        // After a quarter second, a quarter of the rate is available.
+        bucket.refill(start + Duration::from_millis(250));
+        assert_eq!(bucket.take(10_000), 250);
+
+        // An empty bucket grants nothing until time passes.
+        assert_eq!(bucket.take(1), 0);
+        bucket.refill(start + Duration::from_millis(250));
+        assert_eq!(bucket.take(1), 0);
+
+        // The bucket never holds more than one second's worth.
+        bucket.refill(start + Duration::from_secs(60));
+        assert_eq!(bucket.take(10_000), 1000);
+    }
+
+    #[test]
+    fn sub_token_remainders_carry_over() {
+        let mut bucket = TokenBucket::new(3);
+        let start = Instant::now();
+        bucket.last_refill = start;
+        bucket.available = 0;
+
+        // 100ms at 3 B/s is 0.3 bytes: nothing yet, but nothing lost.
+        bucket.refill(start + Duration::from_millis(100));
+        assert_eq!(bucket.take(1), 0);
+        bucket.refill(start + Duration::from_millis(400));
+        assert_eq!(bucket.take(10), 1);
+    }
+
+    #[test]
+    fn give_back_restores_unused_tokens() {
+        let mut bucket = TokenBucket::new(100);
+        assert_eq!(bucket.take(80), 80);
+        bucket.give_back(30);
+        assert_eq!(bucket.take(100), 50);
+    }
+}
@@ -0,0 +1,325 @@
+use super::tcp::sockaddr_into;
+use super::{AddressFamily, IpNetMatcher};
+use std::ffi::CString;
+use std::io::{Error, Result};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// Resolves a hostname to the addresses it currently maps to, in the
+/// order the system resolver returned them.
+///
+/// This is the host-side building block for a future
+/// `wasi:sockets/ip-name-lookup` implementation, and it follows that
+/// spec's rules rather than `getaddrinfo`'s laxer ones: an input that is
+/// already a numeric IP address (or anything else that is not a plain
+/// hostname, like an empty string or a `host:port` pair) is rejected
+/// with `EINVAL` instead of being echoed back. When `family` is given,
+/// addresses of the other family are filtered out.
+///
+/// Failures keep their character rather than collapsing into one bucket:
+/// a name that does not resolve — NXDOMAIN and friends — reports
+/// `ENOENT` (the spec's name-unresolvable error), while a resolver that
+/// could not be reached right now reports `EAGAIN` (its temporary
+///-resolver-failure), so a guest can tell "wrong name" from "retry in a
+/// moment".
+pub fn resolve_addresses(name: &str, family: Option<AddressFamily>) -> Result<Vec<IpAddr>> {
+    if name.is_empty() || name.contains(':') || name.parse::<IpAddr>().is_ok() {
+        return Err(Error::from_raw_os_error(libc::EINVAL));
+    }
+    let addresses: Vec<IpAddr> = system_lookup(name)?
+        .into_iter()
+        .filter(|ip| match family {
+            None => true,
+            Some(AddressFamily::Inet4) => ip.is_ipv4(),
+            Some(AddressFamily::Inet6) => ip.is_ipv6(),
+        })
+        .collect();
+    if addresses.is_empty() {
+        return Err(Error::from_raw_os_error(libc::ENOENT));
+    }
+    Ok(addresses)
+}
+
+/// One `getaddrinfo` round trip, in resolver order, duplicates removed
+/// by asking for stream sockets only.
+///
+/// The libc call is used directly rather than `ToSocketAddrs` because
+/// the standard library folds every `EAI_*` code into one opaque error,
+/// and telling `EAI_NONAME` apart from `EAI_AGAIN` is the whole point;
+/// see [`gai_errno`].
+fn system_lookup(name: &str) -> Result<Vec<IpAddr>> {
+    let name = match CString::new(name) {
+        Ok(name) => name,
+        Err(_) => return Err(Error::from_raw_os_error(libc::EINVAL)),
+    };
+    let mut hints: libc::addrinfo = unsafe { std::mem::zeroed() };
+    hints.ai_socktype = libc::SOCK_STREAM;
+    let mut list: *mut libc::addrinfo = std::ptr::null_mut();
+    let rc = unsafe { libc::getaddrinfo(name.as_ptr(), std::ptr::null(), &hints, &mut list) };
+    if rc != 0 {
+        return Err(gai_errno(rc));
+    }
+    let mut addresses = Vec::new();
+    let mut cursor = list;
+    while !cursor.is_null() {
+        unsafe {
+            let entry = &*cursor;
+            let mut storage: libc::sockaddr_storage = std::mem::zeroed();
+            let len = (entry.ai_addrlen as usize).min(std::mem::size_of_val(&storage));
+            std::ptr::copy_nonoverlapping(
+                entry.ai_addr as *const u8,
+                &mut storage as *mut _ as *mut u8,
+                len,
+            );
+            if let Ok(addr) = sockaddr_into(&storage) {
+                addresses.push(addr.ip());
+            }
+            cursor = entry.ai_next;
+        }
+    }
+    unsafe { libc::freeaddrinfo(list) };
+    Ok(addresses)
+}
+
+/// Maps a `getaddrinfo` failure code onto the errno its callers report.
+///
+/// `EAI_NONAME`/`EAI_NODATA` are the permanent "no such name" answers
+/// (`ENOENT`), `EAI_AGAIN` is the transient resolver hiccup (`EAGAIN`),
+/// and `EAI_SYSTEM` defers to the real errno the resolver stumbled
+/// over. Anything else — `EAI_FAIL` and the various malformed-request
+/// codes — is a non-recoverable resolver error, reported as `EIO`.
+fn gai_errno(rc: libc::c_int) -> Error {
+    match rc {
+        libc::EAI_NONAME | libc::EAI_NODATA => Error::from_raw_os_error(libc::ENOENT),
+        libc::EAI_AGAIN => Error::from_raw_os_error(libc::EAGAIN),
+        libc::EAI_MEMORY => Error::from_raw_os_error(libc::ENOMEM),
+        libc::EAI_SYSTEM => Error::last_os_error(),
+        _ => Error::from_raw_os_error(libc::EIO),
+    }
+}
+
+/// A bounded, TTL-based cache in front of [`resolve_addresses`].
+///
+/// Guests that repeatedly resolve the same hosts otherwise pay a full
+/// resolver round trip each time; entries here are keyed on the
+/// `(name, family)` pair and served until their TTL lapses. The cache
+/// is size-bounded with least-recently-used eviction, so a guest
+/// resolving many distinct names cannot grow host memory without limit.
+///
+/// An optional allowlist of networks (see [`IpNetMatcher`]) is applied
+/// to the *results* of every lookup, cached or fresh — the cache stores
+/// unfiltered resolver answers, so tightening the allowlist takes
+/// effect immediately rather than whenever the entries expire.
+#[derive(Debug)]
+pub struct DnsCache {
+    ttl: Duration,
+    capacity: usize,
+    /// Entries in least-recently-used order: index 0 is next to evict.
+    entries: Vec<CacheEntry>,
+    allowed: Option<IpNetMatcher>,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    name: String,
+    family: Option<AddressFamily>,
+    addresses: Vec<IpAddr>,
+    resolved_at: Instant,
+}
+
+impl DnsCache {
+    /// Creates a cache holding up to `capacity` entries for `ttl` each.
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        assert!(capacity > 0, "a zero-capacity cache cannot hold anything");
+        Self {
+            ttl,
+            capacity,
+            entries: Vec::new(),
+            allowed: None,
+        }
+    }
+
+    /// Restricts results (cached and fresh alike) to the given
+    /// networks; `None` removes the restriction.
+    pub fn set_allowed_networks(&mut self, allowed: Option<IpNetMatcher>) {
+        self.allowed = allowed;
+    }
+
+    /// Resolves through the cache, consulting the system resolver only
+    /// on a miss or an expired entry.
+    pub fn resolve(&mut self, name: &str, family: Option<AddressFamily>) -> Result<Vec<IpAddr>> {
+        self.resolve_with(name, family, resolve_addresses)
+    }
+
+    /// The cache logic with the resolver factored out, so tests can
+    /// count invocations.
+    fn resolve_with<F>(
+        &mut self,
+        name: &str,
+        family: Option<AddressFamily>,
+        resolver: F,
+    ) -> Result<Vec<IpAddr>>
+    where
+        F: Fn(&str, Option<AddressFamily>) -> Result<Vec<IpAddr>>,
+    {
+        let now = Instant::now();
+        if let Some(index) = self
+            .entries
+            .iter()
+            .position(|entry| entry.name == name && entry.family == family)
+        {
+            if now.duration_since(self.entries[index].resolved_at) < self.ttl {
+                // Refresh the LRU position before serving the hit.
+                let entry = self.entries.remove(index);
+                let addresses = entry.addresses.clone();
+                self.entries.push(entry);
+                return self.gate(addresses);
+            }
+            self.entries.remove(index);
+        }
+        let addresses = resolver(name, family)?;
+        if self.entries.len() == self.capacity {
+            self.entries.remove(0);
+        }
+        self.entries.push(CacheEntry {
+            name: name.to_string(),
+            family,
+            addresses: addresses.clone(),
+            resolved_at: now,
+        });
+        self.gate(addresses)
+    }
+
+    /// Applies the allowed-network filter, turning an all-filtered
+    /// answer into the same `ENOENT` an unresolvable name produces.
+    fn gate(&self, mut addresses: Vec<IpAddr>) -> Result<Vec<IpAddr>> {
+        if let Some(allowed) = &self.allowed {
+            addresses.retain(|&ip| allowed.matches(ip));
+            if addresses.is_empty() {
+                return Err(Error::from_raw_os_error(libc::ENOENT));
+            }
+        }
+        Ok(addresses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn localhost_resolves_to_loopback() {
+        let addresses = resolve_addresses("localhost", None).unwrap();
+        assert!(addresses.iter().all(|ip| ip.is_loopback()));
+
+        let v4_only = resolve_addresses("localhost", Some(AddressFamily::Inet4)).unwrap();
+        assert!(v4_only.iter().all(|ip| ip.is_ipv4() && ip.is_loopback()));
+    }
+
+    #[test]
+    fn numeric_and_malformed_inputs_are_rejected() {
+        for input in &["127.0.0.1", "::1", "fe80::1%eth0", "host:80", ""] {
+            assert_eq!(
+                resolve_addresses(input, None).unwrap_err().raw_os_error(),
+                Some(libc::EINVAL),
+                "input {:?} should have been rejected",
+                input
+            );
+        }
+    }
+
+    use std::cell::Cell;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn cache_hits_skip_the_resolver() {
+        let calls = Cell::new(0usize);
+        let resolver = |_: &str, _: Option<AddressFamily>| {
+            calls.set(calls.get() + 1);
+            Ok(vec![IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))])
+        };
+
+        let mut cache = DnsCache::new(Duration::from_secs(60), 2);
+        cache.resolve_with("one.example", None, &resolver).unwrap();
+        cache.resolve_with("one.example", None, &resolver).unwrap();
+        assert_eq!(calls.get(), 1, "second lookup should have been a hit");
+
+        // Same name, different family: a distinct key.
+        cache
+            .resolve_with("one.example", Some(AddressFamily::Inet4), &resolver)
+            .unwrap();
+        assert_eq!(calls.get(), 2);
+
+        // The capacity is two, so a third key evicts the least recently
+        // used and the evictee resolves afresh next time.
+        cache.resolve_with("two.example", None, &resolver).unwrap();
+        assert_eq!(calls.get(), 3);
+        cache.resolve_with("one.example", None, &resolver).unwrap();
+        assert_eq!(calls.get(), 4, "evicted entry should have re-resolved");
+    }
+
+    #[test]
+    fn expired_entries_re_resolve() {
+        let calls = Cell::new(0usize);
+        let resolver = |_: &str, _: Option<AddressFamily>| {
+            calls.set(calls.get() + 1);
+            Ok(vec![IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))])
+        };
+        let mut cache = DnsCache::new(Duration::from_millis(0), 4);
+        cache.resolve_with("x.example", None, &resolver).unwrap();
+        cache.resolve_with("x.example", None, &resolver).unwrap();
+        assert_eq!(calls.get(), 2, "zero TTL should never serve a hit");
+    }
+
+    #[test]
+    fn allowlist_gates_cache_hits_too() {
+        let resolver = |_: &str, _: Option<AddressFamily>| {
+            Ok(vec![
+                IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+                IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+            ])
+        };
+        let mut cache = DnsCache::new(Duration::from_secs(60), 4);
+        let both = cache.resolve_with("svc.example", None, &resolver).unwrap();
+        assert_eq!(both.len(), 2);
+
+        // Tightening the allowlist filters the already-cached answer.
+        let mut allowed = IpNetMatcher::new();
+        allowed.allow(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0)), 8);
+        cache.set_allowed_networks(Some(allowed));
+        let gated = cache.resolve_with("svc.example", None, &resolver).unwrap();
+        assert_eq!(gated, vec![IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))]);
+    }
+
+    #[test]
+    fn unresolvable_names_report_a_clean_error() {
+        // `.invalid` is reserved (RFC 6761) to never resolve. Whether
+        // it reports as unresolvable or as a resolver hiccup depends on
+        // the environment's resolver, but it must be one of the two.
+        let errno = resolve_addresses("name.invalid", None)
+            .unwrap_err()
+            .raw_os_error();
+        assert!(
+            errno == Some(libc::ENOENT) || errno == Some(libc::EAGAIN),
+            "unexpected errno: {:?}",
+            errno
+        );
+    }
+
+    #[test]
+    fn resolver_failures_keep_their_character() {
+        assert_eq!(
+            gai_errno(libc::EAI_NONAME).raw_os_error(),
+            Some(libc::ENOENT)
+        );
+        assert_eq!(
+            gai_errno(libc::EAI_NODATA).raw_os_error(),
+            Some(libc::ENOENT)
+        );
+        assert_eq!(
+            gai_errno(libc::EAI_AGAIN).raw_os_error(),
+            Some(libc::EAGAIN)
+        );
+        assert_eq!(gai_errno(libc::EAI_FAIL).raw_os_error(), Some(libc::EIO));
+    }
+}
@@ -0,0 +1,216 @@
+use super::cvt;
+use std::io::{Error, Result};
+use std::net::SocketAddr;
+use std::os::unix::prelude::*;
+use std::time::Instant;
+
+/// Configuration for tunneling outbound connections through a SOCKS5
+/// proxy (RFC 1928).
+///
+/// Installed on a `NetworkContext`, every connect made by sockets
+/// created afterwards first dials the proxy and issues a `CONNECT` for
+/// the real target; the guest sees an ordinary connection. Both the
+/// no-authentication method and username/password authentication
+/// (RFC 1929) are supported.
+#[derive(Debug, Clone)]
+pub struct Socks5Proxy {
+    addr: SocketAddr,
+    auth: Option<(String, String)>,
+}
+
+impl Socks5Proxy {
+    /// A proxy reachable at `addr` offering the no-authentication
+    /// method.
+    pub fn new(addr: SocketAddr) -> Self {
+        Self { addr, auth: None }
+    }
+
+    /// A proxy requiring username/password authentication. Both fields
+    /// are length-limited to 255 bytes by the wire format; longer
+    /// values are rejected at handshake time.
+    pub fn with_auth(addr: SocketAddr, username: &str, password: &str) -> Self {
+        Self {
+            addr,
+            auth: Some((username.to_string(), password.to_string())),
+        }
+    }
+
+    /// The address of the proxy itself — what the socket actually
+    /// connects to.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+/// Runs the SOCKS5 handshake for `target` over `fd`, which must already
+/// be connected to the proxy. The descriptor is non-blocking, as
+/// everything in this module is, so each wire exchange polls with the
+/// remaining time before `deadline` and fails with `ETIMEDOUT` if the
+/// proxy stalls.
+pub(super) fn establish(
+    fd: RawFd,
+    proxy: &Socks5Proxy,
+    target: SocketAddr,
+    deadline: Instant,
+) -> Result<()> {
+    // Method negotiation: offer exactly the one method we can follow
+    // through on.
+    let offered_method = match proxy.auth {
+        None => 0x00,
+        Some(_) => 0x02,
+    };
+    send_all(fd, &[0x05, 0x01, offered_method], deadline)?;
+    let mut choice = [0u8; 2];
+    recv_exact(fd, &mut choice, deadline)?;
+    if choice[0] != 0x05 {
+        return Err(Error::from_raw_os_error(libc::EPROTO));
+    }
+    if choice[1] != offered_method {
+        // 0xff ("no acceptable methods") and any surprise choice both
+        // mean we cannot proceed.
+        return Err(Error::from_raw_os_error(libc::EACCES));
+    }
+
+    if let Some((username, password)) = &proxy.auth {
+        if username.len() > 255 || password.len() > 255 {
+            return Err(Error::from_raw_os_error(libc::EINVAL));
+        }
+        let mut request = Vec::with_capacity(3 + username.len() + password.len());
+        request.push(0x01);
+        request.push(username.len() as u8);
+        request.extend_from_slice(username.as_bytes());
+        request.push(password.len() as u8);
+        request.extend_from_slice(password.as_bytes());
+        send_all(fd, &request, deadline)?;
+        let mut status = [0u8; 2];
+        recv_exact(fd, &mut status, deadline)?;
+        if status[1] != 0x00 {
+            return Err(Error::from_raw_os_error(libc::EACCES));
+        }
+    }
+
+    // CONNECT request for the real target, always by literal address —
+    // name resolution is the resolver's business, not the proxy's.
+    let mut request = vec![0x05, 0x01, 0x00];
+    match target {
+        SocketAddr::V4(target) => {
+            request.push(0x01);
+            request.extend_from_slice(&target.ip().octets());
+        }
+        SocketAddr::V6(target) => {
+            request.push(0x04);
+            request.extend_from_slice(&target.ip().octets());
+        }
+    }
+    request.extend_from_slice(&target.port().to_be_bytes());
+    send_all(fd, &request, deadline)?;
+
+    let mut reply = [0u8; 4];
+    recv_exact(fd, &mut reply, deadline)?;
+    if reply[0] != 0x05 {
+        return Err(Error::from_raw_os_error(libc::EPROTO));
+    }
+    if reply[1] != 0x00 {
+        // Map the reply codes onto the errno the direct connect would
+        // have produced.
+        let errno = match reply[1] {
+            0x02 => libc::EACCES,
+            0x03 => libc::ENETUNREACH,
+            0x04 => libc::EHOSTUNREACH,
+            0x05 => libc::ECONNREFUSED,
+            0x06 => libc::ETIMEDOUT,
+            _ => libc::ECONNREFUSED,
+        };
+        return Err(Error::from_raw_os_error(errno));
+    }
+    // Drain the bound-address trailer so it is not mistaken for guest
+    // data.
+    let trailer = match reply[3] {
+        0x01 => 4 + 2,
+        0x04 => 16 + 2,
+        _ => return Err(Error::from_raw_os_error(libc::EPROTO)),
+    };
+    let mut bound = [0u8; 18];
+    recv_exact(fd, &mut bound[..trailer], deadline)?;
+    Ok(())
+}
+
+/// Writes all of `buf`, polling for writability between short writes.
+fn send_all(fd: RawFd, buf: &[u8], deadline: Instant) -> Result<()> {
+    let mut written = 0;
+    while written < buf.len() {
+        let remaining = &buf[written..];
+        let rc = unsafe {
+            libc::send(
+                fd,
+                remaining.as_ptr() as *const libc::c_void,
+                remaining.len(),
+                libc::MSG_NOSIGNAL,
+            )
+        };
+        if rc >= 0 {
+            written += rc as usize;
+            continue;
+        }
+        let err = Error::last_os_error();
+        if err.raw_os_error() != Some(libc::EWOULDBLOCK) {
+            return Err(err);
+        }
+        wait_for(fd, libc::POLLOUT, deadline)?;
+    }
+    Ok(())
+}
+
+/// Reads exactly `buf.len()` bytes, polling for readability in between.
+/// A proxy that hangs up mid-handshake reports `ECONNRESET`.
+fn recv_exact(fd: RawFd, buf: &mut [u8], deadline: Instant) -> Result<()> {
+    let mut read = 0;
+    while read < buf.len() {
+        let remaining = &mut buf[read..];
+        let rc = unsafe {
+            libc::recv(
+                fd,
+                remaining.as_mut_ptr() as *mut libc::c_void,
+                remaining.len(),
+                0,
+            )
+        };
+        if rc > 0 {
+            read += rc as usize;
+            continue;
+        }
+        if rc == 0 {
+            return Err(Error::from_raw_os_error(libc::ECONNRESET));
+        }
+        let err = Error::last_os_error();
+        if err.raw_os_error() != Some(libc::EWOULDBLOCK) {
+            return Err(err);
+        }
+        wait_for(fd, libc::POLLIN, deadline)?;
+    }
+    Ok(())
+}
+
+/// Polls `fd` for `events` with whatever time is left before `deadline`.
+fn wait_for(fd: RawFd, events: libc::c_short, deadline: Instant) -> Result<()> {
+    let now = Instant::now();
+    if now >= deadline {
+        return Err(Error::from_raw_os_error(libc::ETIMEDOUT));
+    }
+    let remaining = deadline.duration_since(now);
+    let millis = remaining
+        .as_secs()
+        .saturating_mul(1000)
+        .saturating_add(u64::from(remaining.subsec_millis()))
+        .max(1)
+        .min(libc::c_int::max_value() as u64) as libc::c_int;
+    let mut pollfd = libc::pollfd {
+        fd,
+        events,
+        revents: 0,
+    };
+    if cvt(unsafe { libc::poll(&mut pollfd, 1, millis) })? == 0 {
+        return Err(Error::from_raw_os_error(libc::ETIMEDOUT));
+    }
+    Ok(())
+}
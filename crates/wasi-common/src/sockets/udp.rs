@@ -0,0 +1,633 @@
+use super::tcp::{
+    set_nonblocking_cloexec, sockaddr_from, sockaddr_into, AddressFamily, SocketFd,
+};
+use super::{cvt, cvt_r, getsockopt_int, setsockopt_int};
+use std::io::{Error, Result};
+use std::mem;
+use std::net::SocketAddr;
+use std::os::unix::prelude::*;
+use std::sync::Arc;
+
+// `IP_RECVTTL` and the RFC 3542 hop-limit delivery options are absent
+// from the libc crate version this workspace pins; values from
+// `<bits/in.h>`/`<bits/in6.h>` on Linux and the Darwin `<netinet/in.h>`
+// elsewhere.
+#[cfg(target_os = "linux")]
+const IP_RECVTTL: libc::c_int = 12;
+#[cfg(not(target_os = "linux"))]
+const IP_RECVTTL: libc::c_int = 24;
+#[cfg(target_os = "linux")]
+const IPV6_RECVHOPLIMIT: libc::c_int = 51;
+#[cfg(not(target_os = "linux"))]
+const IPV6_RECVHOPLIMIT: libc::c_int = 37;
+#[cfg(target_os = "linux")]
+const IPV6_HOPLIMIT: libc::c_int = 52;
+#[cfg(not(target_os = "linux"))]
+const IPV6_HOPLIMIT: libc::c_int = 47;
+
+// The destination-options pair from the same RFC, likewise missing from
+// the pinned libc.
+#[cfg(target_os = "linux")]
+const IPV6_RECVDSTOPTS: libc::c_int = 58;
+#[cfg(not(target_os = "linux"))]
+const IPV6_RECVDSTOPTS: libc::c_int = 40;
+#[cfg(target_os = "linux")]
+const IPV6_DSTOPTS: libc::c_int = 59;
+#[cfg(not(target_os = "linux"))]
+const IPV6_DSTOPTS: libc::c_int = 50;
+
+// `IP_MTU` is Linux-only (and missing from the pinned libc); value from
+// `<bits/in.h>`.
+#[cfg(target_os = "linux")]
+const IP_MTU: libc::c_int = 14;
+
+// `IPV6_DONTFRAG` shares the value 62 on Linux and the BSDs but is
+// missing from the pinned libc.
+const IPV6_DONTFRAG: libc::c_int = 62;
+
+/// The lifecycle of a [`SystemUdpSocket`], the (much shorter) datagram
+/// counterpart to `TcpState`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UdpState {
+    /// Freshly created; not usable for traffic yet.
+    Default,
+    /// Bound to a local address; unconnected sends and receives work.
+    Bound,
+    /// Connected to a default peer; plain `send`/`receive` work and
+    /// traffic from other sources is filtered out by the kernel.
+    Connected,
+}
+
+/// A host-owned UDP socket, the datagram sibling of `SystemTcpSocket`.
+///
+/// The descriptor is non-blocking and close-on-exec from the start, so
+/// sends and receives fail with `EWOULDBLOCK` rather than parking the
+/// host; [`poll_recv`]/[`poll_send`] answer readiness questions without
+/// consuming anything. The free functions in this module (TTL delivery,
+/// path-MTU controls, ...) all operate on [`raw`] descriptors and so
+/// compose with this type directly.
+///
+/// [`poll_recv`]: Self::poll_recv
+/// [`poll_send`]: Self::poll_send
+/// [`raw`]: Self::raw
+#[derive(Debug)]
+pub struct SystemUdpSocket {
+    fd: Arc<SocketFd>,
+    state: UdpState,
+    family: AddressFamily,
+}
+
+impl SystemUdpSocket {
+    pub fn new(family: AddressFamily) -> Result<Self> {
+        let domain = match family {
+            AddressFamily::Inet4 => libc::AF_INET,
+            AddressFamily::Inet6 => libc::AF_INET6,
+        };
+        let fd = cvt(unsafe { libc::socket(domain, libc::SOCK_DGRAM, 0) })?;
+        let socket = Self {
+            fd: Arc::new(SocketFd::new(fd)),
+            state: UdpState::Default,
+            family,
+        };
+        set_nonblocking_cloexec(fd)?;
+        Ok(socket)
+    }
+
+    /// Returns the current lifecycle state.
+    pub fn state(&self) -> UdpState {
+        self.state
+    }
+
+    /// Returns the address family the socket was created with.
+    pub fn address_family(&self) -> AddressFamily {
+        self.family
+    }
+
+    /// Returns the raw descriptor, for use with this module's free
+    /// functions. Ownership stays with the socket.
+    pub fn raw(&self) -> RawFd {
+        self.fd.raw
+    }
+
+    /// Binds the socket to a local address.
+    pub fn bind(&mut self, local: SocketAddr) -> Result<()> {
+        if self.state != UdpState::Default {
+            return Err(Error::from_raw_os_error(libc::EINVAL));
+        }
+        let (addr, len) = sockaddr_from(&local);
+        cvt(unsafe { libc::bind(self.raw(), &addr as *const _ as *const libc::sockaddr, len) })?;
+        self.state = UdpState::Bound;
+        Ok(())
+    }
+
+    /// Returns the locally bound address.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        unsafe {
+            let mut storage: libc::sockaddr_storage = mem::zeroed();
+            let mut len = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+            cvt(libc::getsockname(
+                self.raw(),
+                &mut storage as *mut _ as *mut libc::sockaddr,
+                &mut len,
+            ))?;
+            sockaddr_into(&storage)
+        }
+    }
+
+    /// Sets the default peer. Unlike TCP this completes immediately: no
+    /// packets are exchanged, the kernel merely records the address and
+    /// starts filtering inbound traffic to it. Implicitly binds an
+    /// ephemeral local address if the socket was not yet bound.
+    pub fn connect(&mut self, remote: SocketAddr) -> Result<()> {
+        let (addr, len) = sockaddr_from(&remote);
+        cvt(unsafe {
+            libc::connect(self.raw(), &addr as *const _ as *const libc::sockaddr, len)
+        })?;
+        self.state = UdpState::Connected;
+        Ok(())
+    }
+
+    /// Clears the default peer, returning the socket to `Bound`; done by
+    /// connecting to the `AF_UNSPEC` family, per POSIX.
+    pub fn disconnect(&mut self) -> Result<()> {
+        if self.state != UdpState::Connected {
+            return Err(Error::from_raw_os_error(libc::ENOTCONN));
+        }
+        unsafe {
+            let mut addr: libc::sockaddr_storage = mem::zeroed();
+            addr.ss_family = libc::AF_UNSPEC as libc::sa_family_t;
+            let rc = libc::connect(
+                self.raw(),
+                &addr as *const _ as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t,
+            );
+            // Some platforms report EAFNOSUPPORT for the AF_UNSPEC
+            // disconnect even though it worked.
+            if rc == -1 {
+                let err = Error::last_os_error();
+                if err.raw_os_error() != Some(libc::EAFNOSUPPORT) {
+                    return Err(err);
+                }
+            }
+        }
+        self.state = UdpState::Bound;
+        Ok(())
+    }
+
+    /// Sends a datagram to an explicit destination. Refused with
+    /// `EISCONN` while connected: Linux would happily route around the
+    /// default peer, but the BSDs refuse, and a consistent refusal here
+    /// keeps misdirected traffic from varying by platform.
+    pub fn send_to(&self, buf: &[u8], remote: SocketAddr) -> Result<usize> {
+        if self.state == UdpState::Connected {
+            return Err(Error::from_raw_os_error(libc::EISCONN));
+        }
+        let (addr, len) = sockaddr_from(&remote);
+        let rc = unsafe {
+            libc::sendto(
+                self.raw(),
+                buf.as_ptr() as *const libc::c_void,
+                buf.len(),
+                libc::MSG_NOSIGNAL,
+                &addr as *const _ as *const libc::sockaddr,
+                len,
+            )
+        };
+        if rc < 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(rc as usize)
+    }
+
+    /// Sends a datagram to the connected peer.
+    pub fn send(&self, buf: &[u8]) -> Result<usize> {
+        if self.state != UdpState::Connected {
+            return Err(Error::from_raw_os_error(libc::ENOTCONN));
+        }
+        let rc = unsafe {
+            libc::send(
+                self.raw(),
+                buf.as_ptr() as *const libc::c_void,
+                buf.len(),
+                libc::MSG_NOSIGNAL,
+            )
+        };
+        if rc < 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(rc as usize)
+    }
+
+    /// Receives a single datagram along with its source address.
+    pub fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
+        unsafe {
+            let mut storage: libc::sockaddr_storage = mem::zeroed();
+            let mut len = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+            let rc = libc::recvfrom(
+                self.raw(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                0,
+                &mut storage as *mut _ as *mut libc::sockaddr,
+                &mut len,
+            );
+            if rc < 0 {
+                return Err(Error::last_os_error());
+            }
+            Ok((rc as usize, sockaddr_into(&storage)?))
+        }
+    }
+
+    /// Receives a single datagram from the connected peer.
+    pub fn receive(&self, buf: &mut [u8]) -> Result<usize> {
+        if self.state != UdpState::Connected {
+            return Err(Error::from_raw_os_error(libc::ENOTCONN));
+        }
+        let rc = unsafe {
+            libc::recv(self.raw(), buf.as_mut_ptr() as *mut libc::c_void, buf.len(), 0)
+        };
+        if rc < 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(rc as usize)
+    }
+
+    /// Returns whether a datagram is waiting, without consuming it.
+    pub fn poll_recv(&self) -> Result<bool> {
+        self.poll_ready(libc::POLLIN)
+    }
+
+    /// Returns whether a send would be accepted right now.
+    pub fn poll_send(&self) -> Result<bool> {
+        self.poll_ready(libc::POLLOUT)
+    }
+
+    fn poll_ready(&self, events: libc::c_short) -> Result<bool> {
+        let mut pollfd = libc::pollfd {
+            fd: self.raw(),
+            events,
+            revents: 0,
+        };
+        cvt(unsafe { libc::poll(&mut pollfd, 1, 0) })?;
+        Ok(pollfd.revents & events != 0)
+    }
+}
+
+/// Enables (or disables) delivery of the TTL (IPv4) or hop limit (IPv6)
+/// each datagram arrived with as ancillary data, so that it can be read
+/// back through [`recv_with_ttl`].
+pub fn set_recv_ttl(fd: RawFd, family: AddressFamily, enabled: bool) -> Result<()> {
+    let (level, option) = match family {
+        AddressFamily::Inet4 => (libc::IPPROTO_IP, IP_RECVTTL),
+        AddressFamily::Inet6 => (libc::IPPROTO_IPV6, IPV6_RECVHOPLIMIT),
+    };
+    let value: libc::c_int = if enabled { 1 } else { 0 };
+    cvt_r(|| unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            option,
+            &value as *const _ as *const libc::c_void,
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    })?;
+    Ok(())
+}
+
+/// Ancillary metadata captured alongside one received datagram. Each
+/// field is only populated if delivery of that item was enabled on the
+/// socket beforehand (and, for extension headers, if the packet actually
+/// carried one).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DatagramInfo {
+    /// TTL (IPv4) or hop limit (IPv6); see [`set_recv_ttl`].
+    pub ttl: Option<u8>,
+    /// Length in bytes of the IPv6 destination-options extension header,
+    /// if the packet carried one; see [`set_recv_dstopts`].
+    pub dst_opts_len: Option<usize>,
+}
+
+/// Receives a single datagram, returning the number of bytes read along
+/// with the TTL or hop limit the packet arrived with.
+///
+/// The TTL is only present if the socket was configured with
+/// [`set_recv_ttl`]; otherwise `None` is returned alongside the data.
+pub fn recv_with_ttl(fd: RawFd, buf: &mut [u8]) -> Result<(usize, Option<u8>)> {
+    let (nread, info) = recv_with_info(fd, buf)?;
+    Ok((nread, info.ttl))
+}
+
+/// Receives a single datagram along with whatever ancillary metadata the
+/// socket is configured to deliver.
+pub fn recv_with_info(fd: RawFd, buf: &mut [u8]) -> Result<(usize, DatagramInfo)> {
+    // Control-message buffer with the alignment recvmsg expects.
+    #[repr(C)]
+    union CmsgSpace {
+        hdr: libc::cmsghdr,
+        space: [u8; 256],
+    }
+
+    unsafe {
+        let mut iov = libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+        let mut control: CmsgSpace = mem::zeroed();
+        let mut msg: libc::msghdr = mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = &mut control as *mut _ as *mut libc::c_void;
+        msg.msg_controllen = mem::size_of::<CmsgSpace>() as _;
+
+        let nread = libc::recvmsg(fd, &mut msg, 0);
+        if nread < 0 {
+            return Err(Error::last_os_error());
+        }
+
+        let mut info = DatagramInfo::default();
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            let hdr = &*cmsg;
+            let is_ttl = (hdr.cmsg_level == libc::IPPROTO_IP && hdr.cmsg_type == libc::IP_TTL)
+                || (hdr.cmsg_level == libc::IPPROTO_IPV6 && hdr.cmsg_type == IPV6_HOPLIMIT);
+            if is_ttl {
+                let value = *(libc::CMSG_DATA(cmsg) as *const libc::c_int);
+                info.ttl = Some(value as u8);
+            }
+            if hdr.cmsg_level == libc::IPPROTO_IPV6 && hdr.cmsg_type == IPV6_DSTOPTS {
+                info.dst_opts_len =
+                    Some(hdr.cmsg_len as usize - libc::CMSG_LEN(0) as usize);
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+        Ok((nread as usize, info))
+    }
+}
+
+/// Enables (or disables) delivery of the IPv6 destination-options
+/// extension header of each datagram as ancillary data, surfaced through
+/// [`recv_with_info`]. Only meaningful on IPv6 sockets.
+pub fn set_recv_dstopts(fd: RawFd, enabled: bool) -> Result<()> {
+    setsockopt_int(
+        fd,
+        libc::IPPROTO_IPV6,
+        IPV6_RECVDSTOPTS,
+        if enabled { 1 } else { 0 },
+    )
+}
+
+/// Enables or disables strict path-MTU discovery on a UDP socket.
+///
+/// With discovery enabled the kernel sets the don't-fragment bit and
+/// fails oversized sends with `EMSGSIZE` instead of silently fragmenting
+/// them, which lets a guest doing its own reliability segment at the
+/// application layer. Only Linux exposes the `IP_MTU_DISCOVER` knob;
+/// elsewhere this reports `EOPNOTSUPP`.
+#[cfg(target_os = "linux")]
+pub fn set_pmtu_discover(fd: RawFd, family: AddressFamily, enabled: bool) -> Result<()> {
+    let (level, option, on, off) = match family {
+        AddressFamily::Inet4 => (
+            libc::IPPROTO_IP,
+            libc::IP_MTU_DISCOVER,
+            libc::IP_PMTUDISC_DO,
+            libc::IP_PMTUDISC_DONT,
+        ),
+        AddressFamily::Inet6 => (
+            libc::IPPROTO_IPV6,
+            libc::IPV6_MTU_DISCOVER,
+            libc::IP_PMTUDISC_DO,
+            libc::IP_PMTUDISC_DONT,
+        ),
+    };
+    setsockopt_int(fd, level, option, if enabled { on } else { off })
+}
+
+/// See the Linux version; this platform has no equivalent knob.
+#[cfg(not(target_os = "linux"))]
+pub fn set_pmtu_discover(_fd: RawFd, _family: AddressFamily, _enabled: bool) -> Result<()> {
+    Err(Error::from_raw_os_error(libc::EOPNOTSUPP))
+}
+
+/// Returns the path MTU the kernel currently knows for a connected UDP
+/// socket, or `EOPNOTSUPP` on platforms without `IP_MTU`.
+#[cfg(target_os = "linux")]
+pub fn path_mtu(fd: RawFd, family: AddressFamily) -> Result<u32> {
+    let (level, option) = match family {
+        AddressFamily::Inet4 => (libc::IPPROTO_IP, IP_MTU),
+        AddressFamily::Inet6 => (libc::IPPROTO_IPV6, libc::IPV6_MTU),
+    };
+    Ok(getsockopt_int(fd, level, option)? as u32)
+}
+
+/// See the Linux version; this platform has no equivalent knob.
+#[cfg(not(target_os = "linux"))]
+pub fn path_mtu(_fd: RawFd, _family: AddressFamily) -> Result<u32> {
+    Err(Error::from_raw_os_error(libc::EOPNOTSUPP))
+}
+
+/// Sets or clears the don't-fragment bit on a UDP socket.
+///
+/// With DF set, sends larger than the path MTU fail with `EMSGSIZE`
+/// instead of being fragmented at the IP layer. IPv4 has no portable
+/// `IP_DONTFRAG`; on Linux the same effect is had through strict
+/// path-MTU discovery, so this delegates to [`set_pmtu_discover`] there
+/// and reports `EOPNOTSUPP` elsewhere. IPv6 uses `IPV6_DONTFRAG`
+/// directly.
+pub fn set_dont_fragment(fd: RawFd, family: AddressFamily, value: bool) -> Result<()> {
+    match family {
+        AddressFamily::Inet4 => set_pmtu_discover(fd, family, value),
+        AddressFamily::Inet6 => setsockopt_int(
+            fd,
+            libc::IPPROTO_IPV6,
+            IPV6_DONTFRAG,
+            if value { 1 } else { 0 },
+        ),
+    }
+}
+
+/// Checks a received TTL or hop limit against a GTSM (RFC 5082) minimum.
+///
+/// Returns `true` if the packet passes, i.e. arrived with a TTL of at
+/// least `min`. GTSM senders transmit with TTL 255, so a receiver that
+/// expects its peers at most `n` hops away uses `min = 255 - n`.
+pub fn check_minimum_ttl(received: u8, min: u8) -> bool {
+    received >= min
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::UdpSocket;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    fn loopback() -> SocketAddr {
+        use std::net::{IpAddr, Ipv4Addr};
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0)
+    }
+
+    fn wait_for_recv(socket: &SystemUdpSocket) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !socket.poll_recv().unwrap() {
+            assert!(Instant::now() < deadline, "datagram never arrived");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn datagram_round_trip() {
+        let mut a = SystemUdpSocket::new(AddressFamily::Inet4).unwrap();
+        let mut b = SystemUdpSocket::new(AddressFamily::Inet4).unwrap();
+        a.bind(loopback()).unwrap();
+        b.bind(loopback()).unwrap();
+        assert_eq!(a.state(), UdpState::Bound);
+        assert!(!a.poll_recv().unwrap());
+        assert!(a.poll_send().unwrap());
+
+        b.send_to(b"ping", a.local_addr().unwrap()).unwrap();
+        wait_for_recv(&a);
+        let mut buf = [0u8; 16];
+        let (nread, from) = a.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..nread], b"ping");
+        assert_eq!(from, b.local_addr().unwrap());
+    }
+
+    #[test]
+    fn connect_filters_and_disconnect_restores() {
+        let mut socket = SystemUdpSocket::new(AddressFamily::Inet4).unwrap();
+        let mut peer = SystemUdpSocket::new(AddressFamily::Inet4).unwrap();
+        let mut stranger = SystemUdpSocket::new(AddressFamily::Inet4).unwrap();
+        socket.bind(loopback()).unwrap();
+        peer.bind(loopback()).unwrap();
+        stranger.bind(loopback()).unwrap();
+
+        // Plain send/receive require a connected socket.
+        assert_eq!(
+            socket.send(b"early").unwrap_err().raw_os_error(),
+            Some(libc::ENOTCONN)
+        );
+
+        socket.connect(peer.local_addr().unwrap()).unwrap();
+        assert_eq!(socket.state(), UdpState::Connected);
+        // ...and explicit-destination sends are refused while connected.
+        assert_eq!(
+            socket
+                .send_to(b"astray", stranger.local_addr().unwrap())
+                .unwrap_err()
+                .raw_os_error(),
+            Some(libc::EISCONN)
+        );
+        socket.send(b"hello").unwrap();
+        wait_for_recv(&peer);
+        let mut buf = [0u8; 16];
+        let (nread, _) = peer.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..nread], b"hello");
+
+        // Traffic from anyone else is filtered out by the kernel.
+        stranger
+            .send_to(b"intruder", socket.local_addr().unwrap())
+            .unwrap();
+        peer.send_to(b"expected", socket.local_addr().unwrap())
+            .unwrap();
+        wait_for_recv(&socket);
+        let nread = socket.receive(&mut buf).unwrap();
+        assert_eq!(&buf[..nread], b"expected");
+
+        socket.disconnect().unwrap();
+        assert_eq!(socket.state(), UdpState::Bound);
+        assert_eq!(
+            socket.disconnect().unwrap_err().raw_os_error(),
+            Some(libc::ENOTCONN)
+        );
+        // Unconnected operation works again.
+        socket
+            .send_to(b"bye", stranger.local_addr().unwrap())
+            .unwrap();
+        wait_for_recv(&stranger);
+        let (nread, _) = stranger.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..nread], b"bye");
+    }
+
+    #[test]
+    fn gtsm_boundaries() {
+        assert!(check_minimum_ttl(255, 255));
+        assert!(check_minimum_ttl(255, 254));
+        assert!(!check_minimum_ttl(254, 255));
+        assert!(check_minimum_ttl(0, 0));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn oversized_datagram_fails_with_pmtud_enabled() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let peer = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.connect(peer.local_addr().unwrap()).unwrap();
+        set_pmtu_discover(socket.as_raw_fd(), AddressFamily::Inet4, true).unwrap();
+
+        let mtu = path_mtu(socket.as_raw_fd(), AddressFamily::Inet4).unwrap();
+        assert!(mtu >= 576, "implausible path MTU: {}", mtu);
+
+        // Anything larger than the path MTU must be rejected outright
+        // rather than fragmented or dropped.
+        let oversized = vec![0u8; mtu as usize + 1];
+        let err = socket.send(&oversized).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EMSGSIZE));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn dont_fragment_rejects_oversized_sends() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let peer = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.connect(peer.local_addr().unwrap()).unwrap();
+        set_dont_fragment(socket.as_raw_fd(), AddressFamily::Inet4, true).unwrap();
+
+        let mtu = path_mtu(socket.as_raw_fd(), AddressFamily::Inet4).unwrap();
+        let oversized = vec![0u8; mtu as usize + 1];
+        let err = socket.send(&oversized).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EMSGSIZE));
+
+        // Clearing DF restores ordinary (fragmenting) sends for anything
+        // within the UDP length limit.
+        set_dont_fragment(socket.as_raw_fd(), AddressFamily::Inet4, false).unwrap();
+    }
+
+    #[test]
+    fn plain_datagrams_report_no_extension_headers() {
+        let receiver = UdpSocket::bind("[::1]:0").unwrap();
+        let sender = UdpSocket::bind("[::1]:0").unwrap();
+        set_recv_dstopts(receiver.as_raw_fd(), true).unwrap();
+        set_recv_ttl(receiver.as_raw_fd(), AddressFamily::Inet6, true).unwrap();
+
+        sender.send_to(b"plain", receiver.local_addr().unwrap()).unwrap();
+        let mut buf = [0u8; 16];
+        let (nread, info) = recv_with_info(receiver.as_raw_fd(), &mut buf).unwrap();
+        assert_eq!(nread, 5);
+        // No destination-options header on an ordinary datagram, but the
+        // hop limit still comes through.
+        assert_eq!(info.dst_opts_len, None);
+        assert!(info.ttl.is_some());
+    }
+
+    #[test]
+    fn received_ttl_reflects_sender_ttl() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        set_recv_ttl(receiver.as_raw_fd(), AddressFamily::Inet4, true).unwrap();
+        let dst = receiver.local_addr().unwrap();
+
+        for &ttl in &[255u8, 64, 16] {
+            sender.set_ttl(u32::from(ttl)).unwrap();
+            sender.send_to(b"ping", dst).unwrap();
+            let mut buf = [0u8; 16];
+            let (nread, received) = recv_with_ttl(receiver.as_raw_fd(), &mut buf).unwrap();
+            assert_eq!(nread, 4);
+            assert_eq!(received, Some(ttl));
+        }
+
+        // A GTSM receiver expecting directly-connected peers rejects
+        // anything that has been forwarded.
+        assert!(check_minimum_ttl(255, 255));
+        assert!(!check_minimum_ttl(64, 255));
+    }
+}